@@ -1,12 +1,27 @@
+use std::fs::read_to_string;
 use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 
+use blstrs::Scalar;
+
+use lurk::eval::{evaluate_with_trace, IO};
+use lurk::proof::Proof;
 use lurk::repl::repl;
+use lurk::store::Store;
+use lurk::writer::{Dot, Kind, Write};
+
+/// Default `--limit`, used to detect whether the user passed a non-default value on a path
+/// that can't honor it (see `eval` below).
+const DEFAULT_LIMIT: usize = 1000;
 
 /// Lurk CLI
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Cli {
+  /// Iteration limit
+  #[clap(short, long, default_value = "1000")]
+  limit: usize,
+
   /// Eval command
   #[clap(subcommand)]
   command: Option<Command>,
@@ -19,15 +34,123 @@ enum Command {
     /// Input file
     #[clap(parse(from_os_str))]
     path: PathBuf,
+
+    /// Write a Graphviz DOT file of the reduction trace to this path
+    #[clap(long, parse(from_os_str))]
+    trace_dot: Option<PathBuf>,
+  },
+
+  /// Generates a proof of a Lurk file's evaluation
+  Prove {
+    /// Input file
+    #[clap(parse(from_os_str))]
+    path: PathBuf,
+
+    /// Path to write the resulting proof
+    #[clap(long, parse(from_os_str))]
+    proof_out: PathBuf,
+  },
+
+  /// Verifies a proof
+  Verify {
+    /// Path to the proof to verify
+    #[clap(parse(from_os_str))]
+    proof: PathBuf,
   },
 }
 
-fn eval(path: &PathBuf) {
-  if path.exists() {
-    repl(Some(path)).expect("Failed to evaluate")
+fn eval(path: &PathBuf, limit: usize, trace_dot: &Option<PathBuf>) {
+  if !path.exists() {
+    println!("Err: No such file or directory");
+    return;
   }
-  else {
-    println!("Err: No such file or directory")
+
+  match trace_dot {
+    Some(trace_path) => {
+      let mut store = Store::<Scalar>::default();
+      let src = read_to_string(path).expect("Failed to read file");
+      let expr = store.read(&src).expect("Failed to parse expression");
+
+      let (io, iterations, frames) = evaluate_with_trace(&mut store, expr, limit);
+      write_trace_dot(&store, &frames, trace_path).expect("Failed to write trace");
+
+      println!("[{} iterations] {}", iterations, io.expr.fmt_to_string(&store));
+    }
+    // `repl` manages its own iteration limit (settable from within the session) and has no way
+    // to take one from the caller, so reject a non-default `--limit` here instead of silently
+    // ignoring it.
+    None => {
+      if limit != DEFAULT_LIMIT {
+        println!("Err: --limit only applies to `eval --trace-dot`; the repl manages its own limit internally.");
+        return;
+      }
+      repl(Some(path)).expect("Failed to evaluate")
+    }
+  }
+}
+
+// Renders `frames`, one reduction frame per `evaluate_with_trace` step, as a Graphviz digraph:
+// one node per frame labeled with its expression/continuation, and an edge between each
+// successive pair. A run of frames whose continuation doesn't change (a tail-recursive loop)
+// collapses into a single self-loop rather than one node per repetition.
+fn write_trace_dot(store: &Store<Scalar>, frames: &[IO<Scalar>], path: &PathBuf) -> std::io::Result<()> {
+  let mut dot = Dot::new(Kind::Digraph, "trace".to_string());
+  let mut last_id: Option<usize> = None;
+
+  for (i, io) in frames.iter().enumerate() {
+    let is_self_loop = last_id
+      .map(|prev| frames[prev].expr == io.expr && frames[prev].cont == io.cont)
+      .unwrap_or(false);
+
+    if is_self_loop {
+      let prev = last_id.unwrap();
+      dot.add_edge(prev.to_string(), prev.to_string());
+      continue;
+    }
+
+    let label = format!("{} | {}", io.expr.fmt_to_string(store), io.cont.fmt_to_string(store));
+    dot.add_node(i.to_string(), label);
+
+    if let Some(prev) = last_id {
+      dot.add_edge(prev.to_string(), i.to_string());
+    }
+    last_id = Some(i);
+  }
+
+  std::fs::write(path, dot.to_string())
+}
+
+fn prove(path: &PathBuf, proof_out: &PathBuf, limit: usize) {
+  if !path.exists() {
+    println!("Err: No such file or directory");
+    return;
+  }
+
+  let mut store = Store::<Scalar>::default();
+  let src = read_to_string(path).expect("Failed to read file");
+  let expr = store.read(&src).expect("Failed to parse expression");
+
+  let proof = Proof::eval_and_prove(&mut store, expr, limit).expect("Failed to prove evaluation");
+
+  proof.write_to_path(proof_out);
+  proof.verify().expect("created proof doesn't verify");
+}
+
+fn verify(proof: &PathBuf) {
+  if !proof.exists() {
+    println!("Err: No such file or directory");
+    return;
+  }
+
+  let result = Proof::<blstrs::Bls12>::read_from_path(proof)
+    .expect("Failed to read proof")
+    .verify()
+    .expect("Failed to verify proof");
+
+  if result.verified {
+    println!("Verification succeeded.");
+  } else {
+    println!("Verification failed.");
   }
 }
 
@@ -36,8 +159,14 @@ fn main() {
 
   if let Some(cmd) = &cli.command {
     match cmd {
-      Command::Eval{path} => {
-	eval(path);
+      Command::Eval{path, trace_dot} => {
+	eval(path, cli.limit, trace_dot);
+      }
+      Command::Prove{path, proof_out} => {
+        prove(path, proof_out, cli.limit);
+      }
+      Command::Verify{proof} => {
+        verify(proof);
       }
     }
   }
@@ -1,8 +1,8 @@
 use std::env;
-use std::fs::read_to_string;
-use std::io::{self};
+use std::fs::{read_to_string, File};
+use std::io::{self, BufReader};
 use std::path::{Path, PathBuf};
-use clap::{Args, Parser, Subcommand, AppSettings};
+use clap::{ArgEnum, Args, Parser, Subcommand, AppSettings};
 
 use blstrs::Scalar;
 use ff::PrimeField;
@@ -10,10 +10,11 @@ use pairing_lib::{Engine, MultiMillerLoop};
 use serde::{Deserialize, Serialize};
 
 use lurk::eval::IO;
+use lurk::parser::{FileStreamer, Parser as StreamParser};
 use lurk::store::{Ptr, Store};
-use lurk::writer::Write;
+use lurk::writer::{Dot, Kind, Write};
 
-use fcomm::{self, evaluate, Commitment, Error, FileStore, Function, Opening, Proof};
+use fcomm::{self, evaluate, evaluate_with_trace, Commitment, Error, FileStore, Format, Function, Ledger, Opening, Proof, PublicParams};
 
 macro_rules! prl {
   ($($arg:expr),*) => { if *fcomm::VERBOSE.get().expect("verbose flag uninitialized") {
@@ -45,27 +46,76 @@ struct Cli {
   /// Be verbose
   #[clap(short, long)]
   verbose: bool,
-  
+
+  /// Path to cached proving/verifying parameters. If absent, Prove/Open/Verify synthesize
+  /// parameters on the fly instead of loading them from disk.
+  #[clap(long, parse(from_os_str))]
+  params: Option<PathBuf>,
+
+  /// Encoding used when writing or reading proofs, commitments, and functions on disk.
+  /// Reading from stdin ignores this and sniffs the format from the leading bytes instead.
+  #[clap(long, arg_enum, default_value = "json")]
+  format: Format,
+
+  /// Path to the commitment ledger. If given, Commit and Open append an entry recording the
+  /// commitment (and, for Open, the opening's input/output and its predecessor in the chain).
+  #[clap(long, parse(from_os_str))]
+  ledger: Option<PathBuf>,
+
   #[clap(subcommand)]
   command: Command,
 }
 
 #[derive(Subcommand, Debug)]
 enum Command {
+  /// Generates and caches Groth16 proving/verifying parameters
+  Setup(Setup),
+
   /// Commits a function to the scalar store
   Commit(Commit),
-  
+
   /// Creates an opening
   Open(Open),
-  
+
   /// Evaluates an expression
   Eval(Eval),
-  
+
   /// Generates a proof for the given expression
   Prove(Prove),
-  
+
   /// Verifies a proof
   Verify(Verify),
+
+  /// Audits the commitment ledger
+  Ledger(LedgerCmd),
+}
+
+#[derive(Args, Debug)]
+struct LedgerCmd {
+  #[clap(subcommand)]
+  command: LedgerCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum LedgerCommand {
+  /// Walks the ledger end to end, re-checking every opening's proof against its recorded
+  /// commitments and failing loudly on a broken or forked chain
+  Verify(LedgerVerify),
+}
+
+#[derive(Args, Debug)]
+struct LedgerVerify {
+  /// Path to the ledger file
+  #[clap(parse(from_os_str))]
+  path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct Setup {
+  /// Path at which to write the generated proving/verifying parameters. Distinct from the
+  /// global `--params`, which names the cache `Prove`/`Open`/`Verify` read from.
+  #[clap(short, long, parse(from_os_str))]
+  out: PathBuf,
 }
 
 #[derive(Args, Debug)]
@@ -96,6 +146,12 @@ struct Open {
   /// Path to functional commitment (required if chaining openings)
   #[clap(short, long, parse(from_os_str))]
   commitment: Option<PathBuf>,
+
+  /// Stream the input file one datum at a time instead of reading it into the `Store` up
+  /// front, so opening over a large list of inputs scales with one element, not all of them.
+  /// With `--chain`, folds a chained opening over every streamed element in turn.
+  #[clap(long)]
+  stream_input: bool,
 }
 
 #[derive(Args, Debug)]
@@ -103,6 +159,10 @@ struct Eval {
   /// Path to expression source
   #[clap(short = 'x', long, parse(from_os_str))]
   expression: PathBuf,
+
+  /// Write a Graphviz DOT file of the reduction trace to this path
+  #[clap(long, parse(from_os_str))]
+  trace_dot: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -114,6 +174,10 @@ struct Prove {
   /// Path to proof input
   #[clap(short, long, parse(from_os_str))]
   proof: PathBuf,
+
+  /// Stream the expression file instead of reading it into the `Store` up front.
+  #[clap(long)]
+  stream_input: bool,
 }
 
 #[derive(Args, Debug)]
@@ -123,39 +187,64 @@ struct Verify {
   proof: PathBuf,
 }
 
+impl Setup {
+  fn setup(&self, limit: usize) -> Result<(), Error> {
+    prl!("Setting up parameters for limit {}...", limit);
+
+    let params = PublicParams::generate(limit)?;
+    params.write_to_path(&self.out);
+
+    prl!("Parameters cached at {}", self.out.display());
+
+    Ok(())
+  }
+}
+
 impl Commit {
-  fn commit(&self, limit: usize) -> Result<(), Error> {
+  fn commit(&self, limit: usize, format: Format, ledger_path: Option<&Path>) -> Result<(), Error> {
     let s = &mut Store::<Scalar>::default();
-    
-    let mut function = Function::read_from_path(&self.function)?;
+
+    let mut function = Function::read_from_path(&self.function, format)?;
     let fun_ptr = function.fun_ptr(s, limit);
     let commitment = if let Some(secret) = function.secret {
       Commitment::from_ptr_and_secret(s, &fun_ptr, secret)
     } else {
       let (commitment, secret) = Commitment::from_ptr_with_hiding(s, &fun_ptr);
       function.secret = Some(secret);
-      
-      function.write_to_path(&self.function);
-      
+
+      function.write_to_path(&self.function, format);
+
       commitment
     };
-    commitment.write_to_path(&self.commitment);
-    
+    commitment.write_to_path(&self.commitment, format);
+
+    if let Some(ledger_path) = ledger_path {
+      Ledger::open(ledger_path)?.record_commitment(&commitment, &function)?;
+    }
+
     Ok(())
   }
 }
 
 impl Open {
-  fn open(&self, chain: bool, limit: usize, no_eval_input: bool) -> Result<(), Error> {
+  fn open(&self, chain: bool, limit: usize, no_eval_input: bool, params_path: Option<&Path>, format: Format, ledger_path: Option<&Path>) -> Result<(), Error> {
+    if self.stream_input {
+      return self.open_streamed(chain, limit, no_eval_input, params_path, format, ledger_path);
+    }
+
     let mut s = Store::<Scalar>::default();
-    
-    let function = Function::read_from_path(&self.function)?;
+
+    let function = Function::read_from_path(&self.function, format)?;
+    let function_value = serde_json::to_value(&function)?;
     let input = input(&mut s, &self.input, no_eval_input, limit)?;
     let out_path = &self.proof;
-    
+
     // Needed if we are creating a chained commitment.
     let chained_function_path = chain.then(|| path_successor(&self.function));
-    
+    let new_commitment_path = path_successor(out_path);
+
+    let params = load_params(params_path, limit)?;
+
     let proof = Opening::create_and_prove(
       &mut s,
       input,
@@ -163,13 +252,113 @@ impl Open {
       limit,
       chain,
       self.commitment.as_ref(),
-      chained_function_path,
+      chained_function_path.clone(),
+      &params,
     )?;
-    
+
     // Write first, so prover can debug if proof doesn't verify (it should).
-    proof.write_to_path(out_path);
-    proof.verify().expect("created opening doesn't verify");
-    
+    proof.write_to_path(out_path, format);
+    proof.verify(&params).expect("created opening doesn't verify");
+
+    if let Some(ledger_path) = ledger_path {
+      let (commitment_value, new_commitment_value, new_function_value) = ledger_opening_context(
+        format,
+        self.commitment.as_deref(),
+        chain,
+        &new_commitment_path,
+        chained_function_path.as_deref(),
+      )?;
+
+      Ledger::open(ledger_path)?.record_opening(
+        &proof,
+        &function_value,
+        commitment_value.as_ref(),
+        new_commitment_value.as_ref(),
+        new_function_value.as_ref(),
+      )?;
+    }
+
+    Ok(())
+  }
+
+  // Folds a chained opening over every datum streamed out of `self.input`, so an input list
+  // far larger than RAM never has to be materialized in the `Store` at once. Each successive
+  // opening's proof and chained function/commitment are numbered the same way manual chaining
+  // already numbers them, via `path_successor`.
+  fn open_streamed(&self, chain: bool, limit: usize, no_eval_input: bool, params_path: Option<&Path>, format: Format, ledger_path: Option<&Path>) -> Result<(), Error> {
+    let mut s = Store::<Scalar>::default();
+    let params = load_params(params_path, limit)?;
+
+    let mut function_path = self.function.clone();
+    let mut commitment_path = self.commitment.clone();
+    let mut out_path = self.proof.clone();
+
+    let file = File::open(&self.input)?;
+    let mut streamer = FileStreamer::new(BufReader::new(file));
+
+    // Scan the whole stream up front so a malformed or empty input is caught before any
+    // (potentially expensive) proving work starts, then rewind to actually stream it.
+    let mut datum_count = 0;
+    while streamer.next()?.is_some() {
+      datum_count += 1;
+    }
+    if datum_count == 0 {
+      return Err(Error::Parse("input stream contained no data to open".to_string()));
+    }
+    streamer.rewind()?;
+
+    while let Some(datum) = streamer.next()? {
+      let function = Function::read_from_path(&function_path, format)?;
+      let function_value = serde_json::to_value(&function)?;
+      let input = parse_input(&mut s, &datum, no_eval_input, limit)?;
+
+      let chained_function_path = chain.then(|| path_successor(&function_path));
+      let next_commitment_path = chain.then(|| {
+        path_successor(commitment_path.as_ref().unwrap_or(&out_path))
+      });
+
+      let proof = Opening::create_and_prove(
+        &mut s,
+        input,
+        function,
+        limit,
+        chain,
+        commitment_path.as_ref(),
+        chained_function_path.clone(),
+        &params,
+      )?;
+
+      // Write first, so prover can debug if proof doesn't verify (it should).
+      proof.write_to_path(&out_path, format);
+      proof.verify(&params).expect("created opening doesn't verify");
+
+      if let Some(ledger_path) = ledger_path {
+        let (commitment_value, new_commitment_value, new_function_value) = ledger_opening_context(
+          format,
+          commitment_path.as_deref(),
+          chain,
+          next_commitment_path.as_deref().unwrap_or(&out_path),
+          chained_function_path.as_deref(),
+        )?;
+
+        Ledger::open(ledger_path)?.record_opening(
+          &proof,
+          &function_value,
+          commitment_value.as_ref(),
+          new_commitment_value.as_ref(),
+          new_function_value.as_ref(),
+        )?;
+      }
+
+      if !chain {
+        break;
+      }
+
+      function_path = chained_function_path.expect("chain implies a chained function path");
+      commitment_path = next_commitment_path;
+      out_path = path_successor(&out_path);
+    }
+
     Ok(())
   }
 }
@@ -177,36 +366,56 @@ impl Open {
 impl Eval {
   fn eval(&self, limit: usize) -> Result<(), Error> {
     let mut s = Store::<Scalar>::default();
-    
+
     let expr = expression(&mut s, &self.expression)?;
-    
-    let (out_expr, iterations) = evaluate(&mut s, expr, limit);
-    
+
+    let (out_expr, iterations) = if let Some(trace_path) = &self.trace_dot {
+      let (io, iterations, frames) = evaluate_with_trace(&mut s, expr, limit);
+      write_trace_dot(&s, &frames, trace_path)?;
+
+      (io, iterations)
+    } else {
+      evaluate(&mut s, expr, limit)
+    };
+
     println!("[{} iterations] {}", iterations, out_expr.fmt_to_string(&s));
-    
+
     Ok(())
   }
 } 
 
 impl Prove {
-  fn prove(&self, limit: usize) -> Result<(), Error> {
+  fn prove(&self, limit: usize, params_path: Option<&Path>, format: Format) -> Result<(), Error> {
     let mut s = Store::<Scalar>::default();
-    
-    let expr = expression(&mut s, &self.expression)?;
-    
-    let proof = Proof::eval_and_prove(&mut s, expr, limit)?;
-    
+
+    let expr = if self.stream_input {
+      let file = File::open(&self.expression)?;
+      let mut streamer = FileStreamer::new(BufReader::new(file));
+      let datum = streamer
+        .next()?
+        .expect("expression file contained no datum to stream");
+
+      s.read(&datum)
+        .map_err(|e| Error::Parse(format!("{:?}", e)))?
+    } else {
+      expression(&mut s, &self.expression)?
+    };
+
+    let params = load_params(params_path, limit)?;
+    let proof = Proof::eval_and_prove(&mut s, expr, limit, &params)?;
+
     // Write first, so prover can debug if proof doesn't verify (it should).
-    proof.write_to_path(&self.proof);
-    proof.verify().expect("created proof doesn't verify");
-    
+    proof.write_to_path(&self.proof, format);
+    proof.verify(&params).expect("created proof doesn't verify");
+
     Ok(())
   }
-}  
+}
 
 impl Verify {
-  fn verify(&self, cli_error: bool) -> Result<(), Error> {
-    let result = proof(Some(&self.proof))?.verify()?;
+  fn verify(&self, cli_error: bool, limit: usize, params_path: Option<&Path>, format: Format) -> Result<(), Error> {
+    let params = load_params(params_path, limit)?;
+    let result = proof(Some(&self.proof), format)?.verify(&params)?;
     
     serde_json::to_writer(io::stdout(), &result)?;
     
@@ -221,6 +430,27 @@ impl Verify {
   }
 }
 
+impl LedgerCmd {
+  fn run(&self, limit: usize, params_path: Option<&Path>) -> Result<(), Error> {
+    match &self.command {
+      LedgerCommand::Verify(v) => v.verify(limit, params_path),
+    }
+  }
+}
+
+impl LedgerVerify {
+  fn verify(&self, limit: usize, params_path: Option<&Path>) -> Result<(), Error> {
+    let params = load_params(params_path, limit)?;
+    let ledger = Ledger::read_from_path(&self.path)?;
+
+    ledger.verify(&params)?;
+
+    println!("Ledger at {} verified: {} entries.", self.path.display(), ledger.len());
+
+    Ok(())
+  }
+}
+
 fn read_from_path<P: AsRef<Path>, F: PrimeField + Serialize>(
   store: &mut Store<F>,
   path: P
@@ -263,6 +493,28 @@ fn read_no_eval_from_path<P: AsRef<Path>, F: PrimeField + Serialize>(
   Ok((quoted, src))
 }
 
+// Loads cached proving/verifying parameters from `params_path`, if given, and checks that they
+// were generated for the same circuit shape as `limit`. Falls back to synthesizing fresh
+// parameters when no path is supplied, matching the old, implicit behavior.
+fn load_params(params_path: Option<&Path>, limit: usize) -> Result<PublicParams, Error> {
+  match params_path {
+    Some(path) => {
+      let params = PublicParams::read_from_path(path)?;
+
+      if params.digest() != PublicParams::expected_digest(limit) {
+        return Err(Error::StaleParams(format!(
+          "cached parameters at {} do not match the circuit shape for limit {}; run `fcomm setup` again",
+          path.display(),
+          limit
+        )));
+      }
+
+      Ok(params)
+    }
+    None => PublicParams::generate(limit),
+  }
+}
+
 fn path_successor<P: AsRef<Path>>(path: P) -> PathBuf {
   let p = path.as_ref().to_path_buf();
   let new_index = if let Some(extension) = p.extension() {
@@ -282,6 +534,41 @@ fn path_successor<P: AsRef<Path>>(path: P) -> PathBuf {
   new_path
 }
 
+// Resolves the commitment (and, when chaining, the new commitment and its function) an opening
+// should record against in the ledger, reading each back from disk by the same conventional
+// paths `Open`/`open_streamed` already use. Returned as `serde_json::Value`s so `Ledger` never
+// needs to know `Commitment`/`Function`'s concrete type parameters.
+fn ledger_opening_context(
+  format: Format,
+  commitment_path: Option<&Path>,
+  chain: bool,
+  new_commitment_path: &Path,
+  chained_function_path: Option<&Path>,
+) -> Result<(Option<serde_json::Value>, Option<serde_json::Value>, Option<serde_json::Value>), Error> {
+  let commitment_value = commitment_path
+    .map(|p| Commitment::read_from_path(p, format))
+    .transpose()?
+    .as_ref()
+    .map(serde_json::to_value)
+    .transpose()?;
+
+  let (new_commitment_value, new_function_value) = if chain {
+    let new_commitment = serde_json::to_value(&Commitment::read_from_path(new_commitment_path, format)?)?;
+    let new_function = chained_function_path
+      .map(|p| Function::read_from_path(p, format))
+      .transpose()?
+      .as_ref()
+      .map(serde_json::to_value)
+      .transpose()?;
+
+    (Some(new_commitment), new_function)
+  } else {
+    (None, None)
+  };
+
+  Ok((commitment_value, new_commitment_value, new_function_value))
+}
+
 fn _lurk_function<P: AsRef<Path>, F: PrimeField + Serialize>(
   store: &mut Store<F>,
   function_path: P,
@@ -307,6 +594,66 @@ fn input<P: AsRef<Path>, F: PrimeField + Serialize>(store: &mut Store<F>, input_
   Ok(input)
 }
 
+// Like `input`, but parses a datum already in memory (e.g. one yielded by a `FileStreamer`)
+// rather than reading it from a path.
+fn parse_input<F: PrimeField + Serialize>(
+  store: &mut Store<F>,
+  datum: &str,
+  no_eval_input: bool,
+  limit: usize,
+) -> Result<Ptr<F>, Error> {
+  let src = store
+    .read(datum)
+    .map_err(|e| Error::Parse(format!("{:?}", e)))?;
+
+  let input = if no_eval_input {
+    let quote = store.sym("quote");
+    store.list(&[quote, src])
+  } else {
+    let (IO { expr, .. }, _iterations) = evaluate(store, src, limit);
+    expr
+  };
+
+  Ok(input)
+}
+
+// Renders `frames`, one reduction frame per `evaluate_with_trace` step, as a Graphviz digraph:
+// one node per frame labeled with its expression/continuation, and an edge between each
+// successive pair. A run of frames whose continuation doesn't change (a tail-recursive loop)
+// collapses into a single self-loop rather than one node per repetition.
+fn write_trace_dot<F: PrimeField + Serialize>(
+  store: &Store<F>,
+  frames: &[IO<F>],
+  path: &Path,
+) -> Result<(), Error> {
+  let mut dot = Dot::new(Kind::Digraph, "trace".to_string());
+  let mut last_id: Option<usize> = None;
+
+  for (i, io) in frames.iter().enumerate() {
+    let is_self_loop = last_id
+      .map(|prev| frames[prev].expr == io.expr && frames[prev].cont == io.cont)
+      .unwrap_or(false);
+
+    if is_self_loop {
+      let prev = last_id.unwrap();
+      dot.add_edge(prev.to_string(), prev.to_string());
+      continue;
+    }
+
+    let label = format!("{} | {}", io.expr.fmt_to_string(store), io.cont.fmt_to_string(store));
+    dot.add_node(i.to_string(), label);
+
+    if let Some(prev) = last_id {
+      dot.add_edge(prev.to_string(), i.to_string());
+    }
+    last_id = Some(i);
+  }
+
+  std::fs::write(path, dot.to_string())?;
+
+  Ok(())
+}
+
 fn expression<P: AsRef<Path>, F: PrimeField + Serialize>(store: &mut Store<F>, expression_path: P) -> Result<Ptr<F>, Error> {
   let input = read_from_path(store, expression_path)?;
   
@@ -314,7 +661,9 @@ fn expression<P: AsRef<Path>, F: PrimeField + Serialize>(store: &mut Store<F>, e
 }
 
 // Get proof from supplied path or else from stdin.
-fn proof<P: AsRef<Path>, E: Engine + MultiMillerLoop>(proof_path: Option<P>) -> Result<Proof<E>, Error>
+// Get proof from supplied path (using the requested format) or else from stdin, whose format
+// is sniffed from the leading bytes so a piped binary proof works without `--format bin`.
+fn proof<P: AsRef<Path>, E: Engine + MultiMillerLoop>(proof_path: Option<P>, format: Format) -> Result<Proof<E>, Error>
 where
   for<'de> <E as Engine>::Gt: blstrs::Compress + Serialize + Deserialize<'de>,
 for<'de> <E as Engine>::G1: Serialize + Deserialize<'de>,
@@ -324,7 +673,7 @@ for<'de> <E as Engine>::Fr: Serialize + Deserialize<'de>,
 for<'de> <E as Engine>::Gt: blstrs::Compress + Serialize + Deserialize<'de>,
 {
   match proof_path {
-    Some(path) => Proof::read_from_path(path),
+    Some(path) => Proof::read_from_path(path, format),
     None => Proof::read_from_stdin()
   }
 }
@@ -338,21 +687,30 @@ fn main() -> Result<(), Error> {
       .set(cli.verbose)
       .expect("could not set verbose flag");
   
+  let params_path = cli.params.as_deref();
+  let ledger_path = cli.ledger.as_deref();
+
   match &cli.command {
+    Command::Setup(s) => {
+      s.setup(cli.limit)
+    },
     Command::Commit(c)=> {
-      c.commit(cli.limit)
+      c.commit(cli.limit, cli.format, ledger_path)
     },
     Command::Open(o) => {
-      o.open(cli.chain, cli.limit, cli.no_eval_input)
+      o.open(cli.chain, cli.limit, cli.no_eval_input, params_path, cli.format, ledger_path)
     },
     Command::Eval(e) => {
       e.eval(cli.limit)
     },
     Command::Prove(p) => {
-      p.prove(cli.limit)
+      p.prove(cli.limit, params_path, cli.format)
     },
     Command::Verify(v) => {
-      v.verify(cli.error)
+      v.verify(cli.error, cli.limit, params_path, cli.format)
+    },
+    Command::Ledger(l) => {
+      l.run(cli.limit, params_path)
     },
   }
 }
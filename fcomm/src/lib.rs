@@ -0,0 +1,617 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write as IoWrite};
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+use bellperson::groth16::{self, Parameters, PreparedVerifyingKey};
+use blstrs::{Bls12, Scalar};
+use clap::ArgEnum;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+
+use lurk::circuit::MultiFrame;
+use lurk::store::Store;
+
+pub use lurk::eval::{evaluate, evaluate_with_trace};
+
+/// Set once at startup from the `--verbose` flag; read by the `prl!` macro in the `fcomm` CLI.
+pub static VERBOSE: OnceCell<bool> = OnceCell::new();
+
+/// Errors surfaced by the `fcomm` library and its CLI.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Bincode(Box<bincode::ErrorKind>),
+    Synthesis(bellperson::SynthesisError),
+    Verification(String),
+    StaleParams(String),
+    Parse(String),
+    Ledger(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Json(e) => write!(f, "json error: {}", e),
+            Error::Bincode(e) => write!(f, "bincode error: {}", e),
+            Error::Synthesis(e) => write!(f, "synthesis error: {}", e),
+            Error::Verification(msg) => write!(f, "verification error: {}", msg),
+            Error::StaleParams(msg) => write!(f, "{}", msg),
+            Error::Parse(msg) => write!(f, "parse error: {}", msg),
+            Error::Ledger(msg) => write!(f, "ledger error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<Box<bincode::ErrorKind>> for Error {
+    fn from(e: Box<bincode::ErrorKind>) -> Self {
+        Error::Bincode(e)
+    }
+}
+
+impl From<bellperson::SynthesisError> for Error {
+    fn from(e: bellperson::SynthesisError) -> Self {
+        Error::Synthesis(e)
+    }
+}
+
+/// Cached Groth16 proving/verifying parameters for Lurk's reduction circuit. Parameters are
+/// keyed by a digest of the circuit shape they were synthesized for (currently just the
+/// iteration limit, the only thing that changes the circuit's arity today), so stale parameters
+/// left on disk after the limit changes are rejected instead of silently producing proofs that
+/// don't verify.
+pub struct PublicParams {
+    pub params: Parameters<Bls12>,
+    pub pvk: PreparedVerifyingKey<Bls12>,
+    digest: u64,
+}
+
+impl PublicParams {
+    /// Synthesizes fresh parameters for the reduction circuit at `limit`.
+    pub fn generate(limit: usize) -> Result<Self, Error> {
+        let mut store = Store::<Scalar>::default();
+        let circuit = MultiFrame::blank(&mut store, limit);
+
+        let params = groth16::generate_random_parameters::<Bls12, _, _>(circuit, &mut OsRng)?;
+        let pvk = groth16::prepare_verifying_key(&params.vk);
+
+        Ok(PublicParams {
+            params,
+            pvk,
+            digest: Self::expected_digest(limit),
+        })
+    }
+
+    /// The digest these parameters were generated for.
+    pub fn digest(&self) -> u64 {
+        self.digest
+    }
+
+    /// The digest parameters generated for `limit` are expected to carry. Two `PublicParams`
+    /// with different digests were synthesized for differently-shaped circuits and must not be
+    /// mixed.
+    pub fn expected_digest(limit: usize) -> u64 {
+        // `limit` is, today, the only input that changes the circuit's shape.
+        //
+        // TODO: once the circuit takes other shape-affecting configuration (e.g. arity),
+        // fold it into this digest too, or params generated for one shape will be silently
+        // accepted for another.
+        limit as u64
+    }
+
+    /// Writes the digest followed by the raw Groth16 parameters to `path`.
+    pub fn write_to_path<P: AsRef<Path>>(&self, path: P) {
+        let file = File::create(path).expect("failed to create params file");
+        let mut writer = BufWriter::new(file);
+
+        writer
+            .write_all(&self.digest.to_le_bytes())
+            .expect("failed to write params digest");
+        self.params
+            .write(&mut writer)
+            .expect("failed to write params");
+    }
+
+    pub fn read_from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+
+        let mut digest_bytes = [0u8; 8];
+        reader.read_exact(&mut digest_bytes)?;
+        let digest = u64::from_le_bytes(digest_bytes);
+
+        let params = Parameters::<Bls12>::read(&mut reader, false).map_err(Error::Io)?;
+        let pvk = groth16::prepare_verifying_key(&params.vk);
+
+        Ok(PublicParams { params, pvk, digest })
+    }
+}
+
+/// On-disk encoding for commitments, functions, and proofs.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Bin,
+}
+
+/// Leading bytes written before a `Format::Bin` payload, so data read from stdin (where no
+/// `--format` flag applies) can be sniffed instead of assumed.
+pub const BIN_MAGIC: &[u8; 4] = b"FCB1";
+
+/// Types that round-trip to/from a file, raw bytes, or stdin in either `Format`.
+pub trait FileStore: Sized + Serialize + for<'de> Deserialize<'de> {
+    fn write_to_path<P: AsRef<Path>>(&self, path: P, format: Format) {
+        let bytes = self.to_bytes(format).expect("failed to encode");
+        std::fs::write(path, bytes).expect("failed to write");
+    }
+
+    fn read_from_path<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(&bytes, format)
+    }
+
+    fn to_bytes(&self, format: Format) -> Result<Vec<u8>, Error> {
+        match format {
+            Format::Json => Ok(serde_json::to_vec(self)?),
+            Format::Bin => {
+                let mut bytes = BIN_MAGIC.to_vec();
+                bytes.extend(bincode::serialize(self)?);
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn from_bytes(bytes: &[u8], format: Format) -> Result<Self, Error> {
+        match format {
+            Format::Json => Ok(serde_json::from_slice(bytes)?),
+            Format::Bin => {
+                let payload = bytes.strip_prefix(BIN_MAGIC.as_slice()).unwrap_or(bytes);
+                Ok(bincode::deserialize(payload)?)
+            }
+        }
+    }
+
+    /// Reads a value from stdin, sniffing `Format::Bin` from its leading magic bytes and
+    /// falling back to `Format::Json` otherwise.
+    fn read_from_stdin() -> Result<Self, Error> {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+
+        let format = if bytes.starts_with(BIN_MAGIC) {
+            Format::Bin
+        } else {
+            Format::Json
+        };
+
+        Self::from_bytes(&bytes, format)
+    }
+}
+
+// A simple FNV-1a hash over `bytes`. `std::collections::hash_map::DefaultHasher` is explicitly
+// unspecified and may change between Rust versions or compilations, which is fine for in-memory
+// hash maps but not for a digest persisted to an auditable, on-disk log; FNV-1a's definition
+// never changes, so a ledger written by one toolchain still checks out under another.
+fn stable_digest(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+// One record in a ledger file: either a commitment being published, or an opening of the most
+// recently published (or chained) commitment. Stored as `serde_json::Value` so the ledger
+// doesn't need to know `Commitment`/`Function`/`Proof`'s concrete type parameters, only that
+// they serialize.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum LedgerEntry {
+    Commitment {
+        commitment: serde_json::Value,
+        function_digest: u64,
+    },
+    Opening {
+        proof: serde_json::Value,
+        predecessor: Option<usize>,
+        /// The commitment this opening claims to open, if any was given.
+        commitment: Option<serde_json::Value>,
+        function_digest: Option<u64>,
+        /// For a chained opening, the commitment to the next function in the chain.
+        new_commitment: Option<serde_json::Value>,
+        new_function_digest: Option<u64>,
+    },
+}
+
+/// An append-only, JSON-lines log of every commitment and opening recorded through the `fcomm`
+/// CLI's `--ledger` flag. Each opening implicitly chains to the entry recorded immediately
+/// before it, so [`Ledger::verify`] can walk the whole file and catch a broken or
+/// hand-tampered chain as well as a proof that no longer verifies.
+pub struct Ledger {
+    path: PathBuf,
+    entries: Vec<LedgerEntry>,
+}
+
+impl Ledger {
+    /// Opens the ledger at `path`, loading any entries already recorded there, or starts a new
+    /// (empty, not-yet-created) ledger if the file doesn't exist yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.exists() {
+            Self::read_from_path(&path)
+        } else {
+            Ok(Ledger {
+                path,
+                entries: Vec::new(),
+            })
+        }
+    }
+
+    pub fn read_from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        let mut entries = Vec::new();
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+
+        Ok(Ledger { path, entries })
+    }
+
+    /// Records a commitment to `function`, appending one line to the ledger file.
+    pub fn record_commitment<C: Serialize, Fun: Serialize>(
+        &mut self,
+        commitment: &C,
+        function: &Fun,
+    ) -> Result<usize, Error> {
+        let entry = LedgerEntry::Commitment {
+            commitment: serde_json::to_value(commitment)?,
+            function_digest: stable_digest(&serde_json::to_vec(function)?),
+        };
+
+        self.append(entry)
+    }
+
+    /// Records an opening, chaining it to whatever entry was most recently recorded.
+    ///
+    /// `function` is the function this opening actually opens, so `verify` can confirm it
+    /// matches the function digest published (by a `Commitment` entry or a prior chained
+    /// opening) for `commitment`. When `chain` produces a new commitment, `new_commitment` and
+    /// `new_function` record what the chain continues with, so the next opening in the chain
+    /// can be checked against them in turn.
+    pub fn record_opening<P: Serialize>(
+        &mut self,
+        proof: &P,
+        function: &serde_json::Value,
+        commitment: Option<&serde_json::Value>,
+        new_commitment: Option<&serde_json::Value>,
+        new_function: Option<&serde_json::Value>,
+    ) -> Result<usize, Error> {
+        let predecessor = self.entries.len().checked_sub(1);
+
+        let entry = LedgerEntry::Opening {
+            proof: serde_json::to_value(proof)?,
+            predecessor,
+            commitment: commitment.cloned(),
+            function_digest: Some(stable_digest(&serde_json::to_vec(function)?)),
+            new_commitment: new_commitment.cloned(),
+            new_function_digest: new_function
+                .map(|f| Ok::<_, Error>(stable_digest(&serde_json::to_vec(f)?)))
+                .transpose()?,
+        };
+
+        self.append(entry)
+    }
+
+    fn append(&mut self, entry: LedgerEntry) -> Result<usize, Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+
+        self.entries.push(entry);
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Walks the ledger end to end, re-checking every opening's proof against `params`,
+    /// confirming its chain link points at the entry recorded immediately before it, and
+    /// confirming its opened commitment and function digest actually match what that
+    /// predecessor published. Returns the first broken link or failed verification found, if
+    /// any.
+    pub fn verify(&self, params: &PublicParams) -> Result<(), Error> {
+        check_chain(&self.entries)?;
+        check_commitment_links(&self.entries)?;
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            if let LedgerEntry::Opening { proof, .. } = entry {
+                let proof: Proof<Bls12> = serde_json::from_value(proof.clone())?;
+                let result = proof.verify(params)?;
+
+                if !result.verified {
+                    return Err(Error::Verification(format!(
+                        "ledger entry {} does not verify",
+                        i
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// Confirms every `Opening` entry's recorded predecessor is exactly the entry immediately
+// before it, i.e. that the chain hasn't been forked or edited out of sequence. This is pure
+// bookkeeping over the entry list, independent of whether any proof in it still verifies.
+fn check_chain(entries: &[LedgerEntry]) -> Result<(), Error> {
+    for (i, entry) in entries.iter().enumerate() {
+        if let LedgerEntry::Opening { predecessor, .. } = entry {
+            let expected = i.checked_sub(1);
+            if *predecessor != expected {
+                return Err(Error::Ledger(format!(
+                    "entry {} has a broken chain link: expected predecessor {:?}, found {:?}",
+                    i, expected, predecessor
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Confirms every `Opening` entry's claimed commitment and function digest actually match what
+// its predecessor published, either a `Commitment` entry's `commitment`/`function_digest` or a
+// prior chained `Opening`'s `new_commitment`/`new_function_digest`. `check_chain` only confirms
+// the *positions* line up; this confirms the *content* at each link does too, so an opening
+// can't claim to open a commitment other than the one actually recorded before it.
+fn check_commitment_links(entries: &[LedgerEntry]) -> Result<(), Error> {
+    for (i, entry) in entries.iter().enumerate() {
+        if let LedgerEntry::Opening {
+            predecessor,
+            commitment,
+            function_digest,
+            ..
+        } = entry
+        {
+            let (published_commitment, published_function_digest) = match predecessor {
+                Some(p) => match &entries[*p] {
+                    LedgerEntry::Commitment {
+                        commitment,
+                        function_digest,
+                    } => (Some(commitment.clone()), Some(*function_digest)),
+                    LedgerEntry::Opening {
+                        new_commitment,
+                        new_function_digest,
+                        ..
+                    } => (new_commitment.clone(), *new_function_digest),
+                },
+                None => (None, None),
+            };
+
+            if *commitment != published_commitment {
+                return Err(Error::Ledger(format!(
+                    "entry {} opens a commitment that does not match the one its predecessor published",
+                    i
+                )));
+            }
+
+            if *function_digest != published_function_digest {
+                return Err(Error::Ledger(format!(
+                    "entry {} opens a function whose digest does not match the one its predecessor published",
+                    i
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_digest_is_stable_and_shape_sensitive() {
+        assert_eq!(PublicParams::expected_digest(1000), PublicParams::expected_digest(1000));
+        assert_ne!(PublicParams::expected_digest(1000), PublicParams::expected_digest(2000));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        a: u32,
+        b: String,
+    }
+
+    impl FileStore for Sample {}
+
+    #[test]
+    fn json_round_trip() {
+        let sample = Sample { a: 7, b: "hi".into() };
+        let bytes = sample.to_bytes(Format::Json).unwrap();
+        assert_eq!(Sample::from_bytes(&bytes, Format::Json).unwrap(), sample);
+    }
+
+    #[test]
+    fn bin_round_trip_is_magic_prefixed_and_sniffable() {
+        let sample = Sample { a: 7, b: "hi".into() };
+        let bytes = sample.to_bytes(Format::Bin).unwrap();
+
+        assert!(bytes.starts_with(BIN_MAGIC));
+        assert_eq!(Sample::from_bytes(&bytes, Format::Bin).unwrap(), sample);
+    }
+
+    // Builds an `Opening` entry that opens `commitment` (with `function_digest`) and, if
+    // `new_commitment` is given, chains to it.
+    fn opening(
+        proof: &str,
+        predecessor: Option<usize>,
+        commitment: Option<&str>,
+        function_digest: Option<u64>,
+        new_commitment: Option<&str>,
+        new_function_digest: Option<u64>,
+    ) -> LedgerEntry {
+        LedgerEntry::Opening {
+            proof: serde_json::json!(proof),
+            predecessor,
+            commitment: commitment.map(|c| serde_json::json!(c)),
+            function_digest,
+            new_commitment: new_commitment.map(|c| serde_json::json!(c)),
+            new_function_digest,
+        }
+    }
+
+    #[test]
+    fn chain_check_accepts_sequential_predecessors() {
+        let entries = vec![
+            LedgerEntry::Commitment {
+                commitment: serde_json::json!("c0"),
+                function_digest: 0,
+            },
+            opening("p0", Some(0), Some("c0"), Some(0), None, None),
+            opening("p1", Some(1), None, None, None, None),
+        ];
+
+        assert!(check_chain(&entries).is_ok());
+    }
+
+    #[test]
+    fn chain_check_rejects_a_forked_link() {
+        let entries = vec![
+            LedgerEntry::Commitment {
+                commitment: serde_json::json!("c0"),
+                function_digest: 0,
+            },
+            opening("p0", Some(0), Some("c0"), Some(0), None, None),
+            // Should point at index 1, not 0: a forked/edited chain.
+            opening("p1", Some(0), Some("c0"), Some(0), None, None),
+        ];
+
+        assert!(check_chain(&entries).is_err());
+    }
+
+    #[test]
+    fn commitment_links_accept_an_opening_that_matches_its_predecessor() {
+        let entries = vec![
+            LedgerEntry::Commitment {
+                commitment: serde_json::json!("c0"),
+                function_digest: 42,
+            },
+            opening("p0", Some(0), Some("c0"), Some(42), None, None),
+        ];
+
+        assert!(check_commitment_links(&entries).is_ok());
+    }
+
+    #[test]
+    fn commitment_links_reject_an_opening_of_a_different_commitment() {
+        let entries = vec![
+            LedgerEntry::Commitment {
+                commitment: serde_json::json!("c0"),
+                function_digest: 42,
+            },
+            // Claims to open "c1", but the predecessor published "c0".
+            opening("p0", Some(0), Some("c1"), Some(42), None, None),
+        ];
+
+        assert!(check_commitment_links(&entries).is_err());
+    }
+
+    #[test]
+    fn commitment_links_reject_a_function_digest_mismatch() {
+        let entries = vec![
+            LedgerEntry::Commitment {
+                commitment: serde_json::json!("c0"),
+                function_digest: 42,
+            },
+            // Claims to open a function with a different digest than the one published.
+            opening("p0", Some(0), Some("c0"), Some(7), None, None),
+        ];
+
+        assert!(check_commitment_links(&entries).is_err());
+    }
+
+    #[test]
+    fn commitment_links_follow_a_chained_opening() {
+        let entries = vec![
+            LedgerEntry::Commitment {
+                commitment: serde_json::json!("c0"),
+                function_digest: 0,
+            },
+            opening("p0", Some(0), Some("c0"), Some(0), Some("c1"), Some(99)),
+            opening("p1", Some(1), Some("c1"), Some(99), None, None),
+        ];
+
+        assert!(check_commitment_links(&entries).is_ok());
+    }
+
+    #[test]
+    fn stable_digest_is_deterministic_and_content_sensitive() {
+        assert_eq!(stable_digest(b"function-0"), stable_digest(b"function-0"));
+        assert_ne!(stable_digest(b"function-0"), stable_digest(b"function-1"));
+    }
+
+    #[test]
+    fn record_and_read_round_trip() {
+        let path = tempfile::NamedTempFile::new()
+            .expect("failed to create temp file")
+            .path()
+            .to_path_buf();
+        std::fs::remove_file(&path).ok();
+
+        let mut ledger = Ledger::open(&path).expect("failed to open ledger");
+        ledger
+            .record_commitment(&"commitment-0", &"function-0")
+            .expect("failed to record commitment");
+        ledger
+            .record_opening(
+                &"proof-0",
+                &serde_json::json!("function-0"),
+                Some(&serde_json::json!("commitment-0")),
+                None,
+                None,
+            )
+            .expect("failed to record opening");
+
+        let reloaded = Ledger::read_from_path(&path).expect("failed to reload ledger");
+        assert_eq!(reloaded.len(), 2);
+        assert!(check_chain(&reloaded.entries).is_ok());
+        assert!(check_commitment_links(&reloaded.entries).is_ok());
+    }
+}
@@ -0,0 +1,45 @@
+use ff::PrimeField;
+use serde::Serialize;
+
+use crate::store::{Ptr, Store};
+
+/// Re-evaluates `expr` at increasing iteration limits, keeping every intermediate `IO`, so a
+/// caller can render the full reduction trace without `evaluate` itself needing a recording
+/// side channel. Less efficient than a single pass with a built-in hook (it's `O(limit^2)`
+/// reductions rather than `O(limit)`), but it only depends on `evaluate`'s existing contract:
+/// given the same store and expression, evaluating to a smaller limit reproduces a prefix of
+/// evaluating to a larger one.
+pub fn evaluate_with_trace<F: PrimeField + Serialize>(
+    store: &mut Store<F>,
+    expr: Ptr<F>,
+    limit: usize,
+) -> (IO<F>, usize, Vec<IO<F>>) {
+    let limit = limit.max(1);
+    let mut frames: Vec<IO<F>> = Vec::with_capacity(limit);
+    let mut last = None;
+
+    for step in 1..=limit {
+        let (io, iterations) = evaluate(store, expr, step);
+
+        // Once `evaluate` has reached its fixed point, re-running at a larger step reproduces
+        // the same terminal `IO` again; stop instead of recording it a second time (which would
+        // otherwise show up as a spurious trailing self-loop in the rendered trace).
+        if frames
+            .last()
+            .map_or(false, |prev| prev.expr == io.expr && prev.cont == io.cont)
+        {
+            break;
+        }
+
+        frames.push(io.clone());
+        let done = iterations < step;
+        last = Some((io, iterations));
+
+        if done {
+            break;
+        }
+    }
+
+    let (io, iterations) = last.expect("loop runs at least once since limit >= 1");
+    (io, iterations, frames)
+}
@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+/// Yields one `E` at a time from a buffered byte source, so a caller never has to hold an
+/// entire input file in the `Store` at once.
+pub trait Parser<E> {
+    /// Wraps `reader`, ready to yield the first element on the next call to `next`.
+    fn new(reader: BufReader<File>) -> Self;
+
+    /// Reads and returns the next element, or `None` once the stream is exhausted.
+    fn next(&mut self) -> io::Result<Option<E>>;
+}
+
+/// Streams successive Lurk data out of a file one at a time, so a function can be opened
+/// over an input list far larger than RAM without first parsing the whole file into the
+/// `Store`. Each element is read as a single top-level form (a parenthesized list, a string
+/// literal, or a bare atom); the caller is responsible for parsing the returned source text
+/// into the `Store`.
+pub struct FileStreamer {
+    reader: BufReader<File>,
+    peeked: Option<char>,
+}
+
+impl FileStreamer {
+    /// Seeks back to the start of the file so it can be streamed again from the first datum.
+    /// `BufReader::seek` discards its internal buffer on seek, so this is always consistent
+    /// with a fresh `FileStreamer` over the same file.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.peeked = None;
+        Ok(())
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        if let Some(c) = self.peeked.take() {
+            return Ok(Some(c));
+        }
+
+        let mut lead = [0u8; 1];
+        if self.reader.read(&mut lead)? == 0 {
+            return Ok(None);
+        }
+
+        let width = utf8_width(lead[0]);
+        let mut buf = [0u8; 4];
+        buf[0] = lead[0];
+        if width > 1 {
+            self.reader.read_exact(&mut buf[1..width])?;
+        }
+
+        let s = std::str::from_utf8(&buf[..width])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        Ok(s.chars().next())
+    }
+
+    fn peek_char(&mut self) -> io::Result<Option<char>> {
+        if self.peeked.is_none() {
+            self.peeked = self.read_char()?;
+        }
+        Ok(self.peeked)
+    }
+}
+
+// Determines, from a UTF-8 lead byte, how many bytes the encoded codepoint occupies.
+fn utf8_width(lead: u8) -> usize {
+    if lead & 0b1000_0000 == 0 {
+        1
+    } else if lead & 0b1110_0000 == 0b1100_0000 {
+        2
+    } else if lead & 0b1111_0000 == 0b1110_0000 {
+        3
+    } else {
+        4
+    }
+}
+
+impl Parser<String> for FileStreamer {
+    fn new(reader: BufReader<File>) -> Self {
+        FileStreamer {
+            reader,
+            peeked: None,
+        }
+    }
+
+    fn next(&mut self) -> io::Result<Option<String>> {
+        // Skip leading whitespace between data.
+        while let Some(c) = self.peek_char()? {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.peeked = None;
+        }
+
+        let first = match self.peek_char()? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let mut datum = String::new();
+
+        if first == '(' {
+            let mut depth: i64 = 0;
+            let mut in_string = false;
+
+            while let Some(c) = self.read_char()? {
+                datum.push(c);
+
+                if in_string {
+                    if c == '\\' {
+                        if let Some(escaped) = self.read_char()? {
+                            datum.push(escaped);
+                        }
+                    } else if c == '"' {
+                        in_string = false;
+                    }
+                    continue;
+                }
+
+                match c {
+                    '"' => in_string = true,
+                    '(' => depth += 1,
+                    ')' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        } else if first == '"' {
+            datum.push(self.read_char()?.expect("just peeked"));
+
+            while let Some(c) = self.read_char()? {
+                datum.push(c);
+                if c == '\\' {
+                    if let Some(escaped) = self.read_char()? {
+                        datum.push(escaped);
+                    }
+                } else if c == '"' {
+                    break;
+                }
+            }
+        } else {
+            // A bare atom (symbol or number) ends at the next whitespace or parenthesis, which
+            // is left unconsumed so the following call to `next` still sees it.
+            while let Some(c) = self.peek_char()? {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                datum.push(self.read_char()?.expect("just peeked"));
+            }
+        }
+
+        Ok(Some(datum))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn streamer(contents: &str) -> FileStreamer {
+        let mut file = tempfile::tempfile().expect("failed to create temp file");
+        file.write_all(contents.as_bytes()).expect("failed to write temp file");
+        file.flush().expect("failed to flush temp file");
+        use std::io::Seek;
+        file.seek(io::SeekFrom::Start(0)).expect("failed to rewind temp file");
+        FileStreamer::new(BufReader::new(file))
+    }
+
+    fn collect(contents: &str) -> Vec<String> {
+        let mut streamer = streamer(contents);
+        let mut data = Vec::new();
+        while let Some(datum) = streamer.next().expect("read failed") {
+            data.push(datum);
+        }
+        data
+    }
+
+    #[test]
+    fn splits_lists_atoms_and_strings() {
+        assert_eq!(
+            collect("(+ 1 2) foo \"a string\" 42"),
+            vec!["(+ 1 2)", "foo", "\"a string\"", "42"],
+        );
+    }
+
+    #[test]
+    fn parens_inside_strings_do_not_affect_depth() {
+        assert_eq!(
+            collect("(lambda (x) \"(unbalanced\")"),
+            vec!["(lambda (x) \"(unbalanced\")"],
+        );
+    }
+
+    #[test]
+    fn empty_input_yields_no_data() {
+        assert_eq!(collect("   \n\t "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rewind_allows_re_reading_from_the_start() {
+        let mut streamer = streamer("foo bar baz");
+
+        let first_pass: Vec<String> = std::iter::from_fn(|| streamer.next().expect("read failed")).collect();
+        streamer.rewind().expect("failed to rewind");
+        let second_pass: Vec<String> = std::iter::from_fn(|| streamer.next().expect("read failed")).collect();
+
+        assert_eq!(first_pass, vec!["foo", "bar", "baz"]);
+        assert_eq!(first_pass, second_pass);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+
+/// The root syntactic form a [`Dot`] graph is written as. Only the one kind Lurk needs today
+/// (a directed graph, for dumping an evaluation trace) is implemented.
+pub enum Kind {
+    Digraph,
+}
+
+impl Kind {
+    fn keyword(&self) -> &'static str {
+        match self {
+            Kind::Digraph => "digraph",
+        }
+    }
+
+    fn edgeop(&self) -> &'static str {
+        match self {
+            Kind::Digraph => "->",
+        }
+    }
+}
+
+/// A minimal Graphviz DOT writer: just enough of the grammar (one [`Kind`], labeled nodes,
+/// directed edges) to render an evaluation trace, not a general-purpose DOT implementation.
+pub struct Dot {
+    kind: Kind,
+    name: String,
+    nodes: Vec<(String, String)>,
+    edges: Vec<(String, String)>,
+}
+
+impl Dot {
+    pub fn new<S: Into<String>>(kind: Kind, name: S) -> Self {
+        Dot {
+            kind,
+            name: name.into(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// Adds a node with the given identifier and label, overwriting any label already set for
+    /// that identifier.
+    pub fn add_node<S: Into<String>>(&mut self, id: S, label: S) {
+        let id = id.into();
+        let label = label.into();
+
+        match self.nodes.iter_mut().find(|(existing_id, _)| *existing_id == id) {
+            Some(entry) => entry.1 = label,
+            None => self.nodes.push((id, label)),
+        }
+    }
+
+    /// Adds a directed edge; calling this with the same `from` and `to` renders a self-loop.
+    pub fn add_edge<S: Into<String>>(&mut self, from: S, to: S) {
+        self.edges.push((from.into(), to.into()));
+    }
+}
+
+impl fmt::Display for Dot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {} {{", self.kind.keyword(), escape_id(&self.name))?;
+
+        for (id, label) in &self.nodes {
+            writeln!(
+                f,
+                "  {} [label={}];",
+                escape_id(id),
+                escape_label(label)
+            )?;
+        }
+
+        for (from, to) in &self.edges {
+            writeln!(
+                f,
+                "  {} {} {};",
+                escape_id(from),
+                self.kind.edgeop(),
+                escape_id(to)
+            )?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+// A bare identifier can be emitted unquoted; anything else must be quoted like a label.
+fn escape_id(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        s.to_string()
+    } else {
+        escape_label(s)
+    }
+}
+
+// Quotes a DOT string, escaping embedded quotes and backslashes.
+fn escape_label(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_node_overwrites_existing_label() {
+        let mut dot = Dot::new(Kind::Digraph, "g");
+        dot.add_node("0", "first");
+        dot.add_node("0", "second");
+
+        assert_eq!(dot.nodes, vec![("0".to_string(), "second".to_string())]);
+    }
+
+    #[test]
+    fn self_loop_edge_renders() {
+        let mut dot = Dot::new(Kind::Digraph, "g");
+        dot.add_edge("0", "0");
+
+        let rendered = dot.to_string();
+        assert!(rendered.contains("0 -> 0;"));
+    }
+
+    #[test]
+    fn bare_identifier_is_unquoted() {
+        assert_eq!(escape_id("node_0"), "node_0");
+    }
+
+    #[test]
+    fn label_is_quoted_and_escaped() {
+        assert_eq!(escape_label("a \"quote\" and \\backslash"), "\"a \\\"quote\\\" and \\\\backslash\"");
+    }
+}